@@ -0,0 +1,423 @@
+//! Module for explaining how a color support level was determined.
+//!
+//! This module provides the `Detection` struct, a diagnostic record of a color support detection:
+//! the resulting level, the reason it was chosen, whether it was forced, the stream it applies to,
+//! the color-related command-line flags that were seen, and a snapshot of the relevant environment
+//! variables.
+//!
+//! With the `serde` feature enabled, `Detection` implements `Serialize`, so command-line tools can
+//! emit it as machine-readable diagnostics (e.g. behind a `--color-info --json` flag).
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::{stderr, stdin, stdout, IsTerminal};
+
+use crate::colors::{resolve_stream_color_level_in, ColorInfo, ColorSupportLevel};
+use crate::environment::Environment;
+use crate::options::{process_var, OutputStreamOptions};
+
+/// Environment variables that take part in detection, captured in a `Detection` snapshot.
+pub const DETECTION_VARS: [&str; 21] = [
+    "TERM",
+    "COLORTERM",
+    "TERM_PROGRAM",
+    "TERM_PROGRAM_VERSION",
+    "FORCE_COLOR",
+    "NO_COLOR",
+    "CLICOLOR",
+    "CLICOLOR_FORCE",
+    "CI",
+    "TEAMCITY_VERSION",
+    "DOMTERM",
+    "LC_COLORTERM",
+    "WT_SESSION",
+    "AGENT_NAME",
+    "XTERM_VERSION",
+    "KITTY_WINDOW_ID",
+    "TERM_COLOR_SUPPORT_MAP",
+    "CLOUD_SHELL",
+    "AWS_EXECUTION_ENV",
+    "ACC_CLOUD",
+    "AZUREPS_HOST_ENVIRONMENT",
+];
+
+/// Runs `f` with every variable in `DETECTION_VARS` cleared except those set in `vars`, so tests do
+/// not depend on the host environment.
+#[cfg(test)]
+pub(crate) fn with_detection_vars<R>(vars: &[(&str, &str)], f: impl FnOnce() -> R) -> R {
+    let cleared = DETECTION_VARS
+        .iter()
+        .filter(|&&name| vars.iter().all(|&(set, _)| set != name))
+        .map(|&name| (name, None));
+    let set = vars.iter().map(|&(name, value)| (name, Some(value)));
+    temp_env::with_vars(cleared.chain(set).collect::<Vec<_>>(), f)
+}
+
+/// Enumeration representing the output stream a detection applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Stream {
+    /// Standard output stream.
+    Stdout,
+    /// Standard error stream.
+    Stderr,
+    /// Standard input stream.
+    Stdin,
+}
+
+/// Enumeration representing why a color support level was chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DetectionReason {
+    /// A level was pushed onto the override stack.
+    Override,
+    /// The `FORCE_COLOR` environment variable forced the level.
+    ForceColorEnv,
+    /// A command-line flag such as `--color` or `--no-color` forced the level.
+    Flag,
+    /// The `NO_COLOR` environment variable disabled color.
+    NoColorEnv,
+    /// The `CLICOLOR_FORCE` environment variable forced color.
+    ClicolorForceEnv,
+    /// `CLICOLOR=0` disabled color.
+    ClicolorEnv,
+    /// The stream is not a terminal.
+    NotATerminal,
+    /// The level was classified from the terminal environment.
+    Environment,
+}
+
+/// Struct representing the outcome of a color support detection and how it was reached.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Detection {
+    /// The detected color support level.
+    pub level: ColorSupportLevel,
+    /// Why the level was chosen.
+    pub reason: DetectionReason,
+    /// Indicates if the level was forced rather than detected.
+    pub forced: bool,
+    /// Indicates if the level comes from an authoritative source, such as forcing, `NO_COLOR`,
+    /// `COLORTERM=truecolor`, a known terminal program or a terminfo entry advertising direct
+    /// color, rather than a guess from the `TERM` name.
+    pub certain: bool,
+    /// The stream the detection applies to.
+    pub stream: Stream,
+    /// Color-related command-line flags that were present.
+    pub flags: Vec<String>,
+    /// Snapshot of the environment variables relevant to detection that were set.
+    pub env: BTreeMap<&'static str, String>,
+}
+
+impl Detection {
+    /// Detects and explains color support for the standard output stream.
+    pub fn stdout() -> Self {
+        let args = std::env::args().collect::<Vec<String>>();
+        let options = OutputStreamOptions::new(Some(stdout().is_terminal()), None);
+        Self::detect(Stream::Stdout, options, &args)
+    }
+
+    /// Detects and explains color support for the standard error stream.
+    pub fn stderr() -> Self {
+        let args = std::env::args().collect::<Vec<String>>();
+        let options = OutputStreamOptions::new(Some(stderr().is_terminal()), None);
+        Self::detect(Stream::Stderr, options, &args)
+    }
+
+    /// Detects and explains color support for the standard input stream.
+    pub fn stdin() -> Self {
+        let args = std::env::args().collect::<Vec<String>>();
+        let options = OutputStreamOptions::new(Some(stdin().is_terminal()), None);
+        Self::detect(Stream::Stdin, options, &args)
+    }
+
+    /// Detects and explains color support for a stream using the given options and arguments.
+    pub fn detect(stream: Stream, options: OutputStreamOptions, args: &[String]) -> Self {
+        Self::detect_with_env(stream, options, args, process_var)
+    }
+
+    /// Detects and explains color support for a stream using the given options and arguments,
+    /// looking up every environment variable through `env` instead of the process environment.
+    pub fn detect_with_env(
+        stream: Stream,
+        options: OutputStreamOptions,
+        args: &[String],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let environment_certain = Cell::new(false);
+        let (level, reason) = resolve_stream_color_level_in(&options, args, &env, || {
+            let (level, certain) = Environment::from_env(&env)
+                .with_config(&options.config)
+                .determine_color_level_with_certainty();
+            environment_certain.set(certain);
+            level
+        });
+
+        Detection {
+            level,
+            reason,
+            forced: matches!(
+                reason,
+                DetectionReason::Override
+                    | DetectionReason::ForceColorEnv
+                    | DetectionReason::Flag
+                    | DetectionReason::ClicolorForceEnv
+            ),
+            certain: reason != DetectionReason::Environment || environment_certain.get(),
+            stream,
+            flags: args
+                .iter()
+                .filter(|arg| is_color_flag(arg))
+                .cloned()
+                .collect(),
+            env: DETECTION_VARS
+                .iter()
+                .filter_map(|&name| env(name).map(|value| (name, value)))
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if the level comes from an authoritative source rather than a heuristic, so
+    /// callers such as TUIs can decide whether to probe the terminal further.
+    pub fn is_certain(&self) -> bool {
+        self.certain
+    }
+
+    /// Returns the color support information for the detected level.
+    pub fn info(&self) -> ColorInfo {
+        ColorInfo::new(self.level)
+    }
+
+    /// Returns actionable advice on how to enable color when no color support was detected, or
+    /// `None` if color is available.
+    pub fn remediation(&self) -> Option<&'static str> {
+        if self.level != ColorSupportLevel::NoColor {
+            return None;
+        }
+
+        let advice = match self.reason {
+            DetectionReason::Override => {
+                "color was disabled programmatically by an override; remove the override"
+            }
+            DetectionReason::ForceColorEnv => "FORCE_COLOR disables color; set FORCE_COLOR=1",
+            DetectionReason::Flag => "color was disabled by a command-line flag; remove --no-color",
+            DetectionReason::NoColorEnv => "NO_COLOR is set; unset NO_COLOR or pass --color=always",
+            DetectionReason::ClicolorForceEnv => {
+                "CLICOLOR_FORCE is set but color is unavailable; set FORCE_COLOR=1"
+            }
+            DetectionReason::ClicolorEnv => "CLICOLOR=0 disables color; unset CLICOLOR",
+            DetectionReason::NotATerminal => match self.stream {
+                Stream::Stdout => {
+                    "stdout is not a terminal; pass --color=always or set FORCE_COLOR=1"
+                }
+                Stream::Stderr => {
+                    "stderr is not a terminal; pass --color=always or set FORCE_COLOR=1"
+                }
+                Stream::Stdin => {
+                    "stdin is not a terminal; pass --color=always or set FORCE_COLOR=1"
+                }
+            },
+            DetectionReason::Environment => {
+                if self.env.get("TERM").map(String::as_str) == Some("dumb") {
+                    "TERM=dumb; set a color-capable TERM such as xterm-256color"
+                } else if self.env.contains_key("CI") {
+                    "CI environment detected without color support; set FORCE_COLOR=1"
+                } else {
+                    "TERM is not recognized as color-capable; set a color-capable TERM such as \
+                     xterm-256color or set FORCE_COLOR=1"
+                }
+            }
+        };
+        Some(advice)
+    }
+}
+
+/// Checks whether a command-line argument is one of the color flags.
+fn is_color_flag(arg: &str) -> bool {
+    let normalized_arg = arg.trim_start_matches('-').to_lowercase();
+    normalized_arg.starts_with("color") || normalized_arg.starts_with("no-color")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overrides::push_override;
+
+    #[test]
+    fn test_detect_override() {
+        let _guard = push_override(ColorSupportLevel::TrueColor);
+        let detection = Detection::detect(
+            Stream::Stdout,
+            OutputStreamOptions::new(Some(false), None),
+            &[String::from("program_name")],
+        );
+        assert_eq!(detection.level, ColorSupportLevel::TrueColor);
+        assert_eq!(detection.reason, DetectionReason::Override);
+        assert!(detection.forced);
+        assert_eq!(
+            detection.info(),
+            ColorInfo::new(ColorSupportLevel::TrueColor)
+        );
+    }
+
+    #[test]
+    fn test_detect_not_a_terminal() {
+        with_detection_vars(&[], || {
+            let detection = Detection::detect(
+                Stream::Stderr,
+                OutputStreamOptions::new(Some(false), None),
+                &[String::from("program_name")],
+            );
+            assert_eq!(detection.level, ColorSupportLevel::NoColor);
+            assert_eq!(detection.reason, DetectionReason::NotATerminal);
+            assert!(!detection.forced);
+            assert_eq!(detection.stream, Stream::Stderr);
+        });
+    }
+
+    #[test]
+    fn test_detect_collects_color_flags() {
+        with_detection_vars(&[], || {
+            let args = vec![
+                String::from("program_name"),
+                String::from("--verbose"),
+                String::from("--color=256"),
+            ];
+            let detection = Detection::detect(
+                Stream::Stdout,
+                OutputStreamOptions::new(Some(false), None),
+                &args,
+            );
+            assert_eq!(detection.level, ColorSupportLevel::Colors256);
+            assert_eq!(detection.reason, DetectionReason::Flag);
+            assert_eq!(detection.flags, vec![String::from("--color=256")]);
+        });
+    }
+
+    /// Detects stdout on a terminal with the given terminal variables and every other terminal
+    /// signal cleared.
+    fn detect_terminal(
+        term: &str,
+        colorterm: Option<&str>,
+        term_program: Option<&str>,
+    ) -> Detection {
+        let mut vars = vec![("TERM", term)];
+        vars.extend(colorterm.map(|colorterm| ("COLORTERM", colorterm)));
+        vars.extend(term_program.map(|term_program| ("TERM_PROGRAM", term_program)));
+        with_detection_vars(&vars, || {
+            Detection::detect(
+                Stream::Stdout,
+                OutputStreamOptions::new(Some(true), None),
+                &[String::from("program_name")],
+            )
+        })
+    }
+
+    #[test]
+    fn test_is_certain() {
+        let _guard = push_override(ColorSupportLevel::Basic);
+        let detection = detect_terminal("xterm", None, None);
+        assert_eq!(detection.reason, DetectionReason::Override);
+        assert!(detection.is_certain());
+    }
+
+    #[test]
+    fn test_is_certain_for_authoritative_environment() {
+        let detection = detect_terminal("xterm", Some("truecolor"), None);
+        assert_eq!(detection.reason, DetectionReason::Environment);
+        assert!(detection.is_certain());
+
+        let detection = detect_terminal("xterm-256color", None, Some("vscode"));
+        assert_eq!(detection.level, ColorSupportLevel::TrueColor);
+        assert!(detection.is_certain());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_is_not_certain_for_term_guess() {
+        // Terminal names without a terminfo entry, so only the name is guessed from.
+        let detection = detect_terminal("unknown-256color", None, None);
+        assert_eq!(detection.reason, DetectionReason::Environment);
+        assert_eq!(detection.level, ColorSupportLevel::Colors256);
+        assert!(!detection.is_certain());
+
+        let detection = detect_terminal("rxvt-unknown", None, None);
+        assert!(!detection.is_certain());
+    }
+
+    #[test]
+    fn test_remediation_for_pipe() {
+        with_detection_vars(&[], || {
+            let detection = Detection::detect(
+                Stream::Stdout,
+                OutputStreamOptions::new(Some(false), None),
+                &[String::from("program_name")],
+            );
+            assert_eq!(
+                detection.remediation(),
+                Some("stdout is not a terminal; pass --color=always or set FORCE_COLOR=1")
+            );
+
+            let detection = Detection::detect(
+                Stream::Stdin,
+                OutputStreamOptions::new(Some(false), None),
+                &[String::from("program_name")],
+            );
+            assert_eq!(
+                detection.remediation(),
+                Some("stdin is not a terminal; pass --color=always or set FORCE_COLOR=1")
+            );
+        });
+    }
+
+    #[test]
+    fn test_remediation_for_dumb_terminal() {
+        with_detection_vars(&[("TERM", "dumb")], || {
+            let detection = Detection::detect(
+                Stream::Stdout,
+                OutputStreamOptions::new(Some(true), None),
+                &[String::from("program_name")],
+            );
+            assert_eq!(detection.reason, DetectionReason::Environment);
+            assert_eq!(
+                detection.remediation(),
+                Some("TERM=dumb; set a color-capable TERM such as xterm-256color")
+            );
+        });
+    }
+
+    #[test]
+    fn test_remediation_none_when_color_available() {
+        let _guard = push_override(ColorSupportLevel::Basic);
+        let detection = Detection::detect(
+            Stream::Stdout,
+            OutputStreamOptions::new(Some(false), None),
+            &[String::from("program_name")],
+        );
+        assert_eq!(detection.remediation(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_detection_json_shape() {
+        with_detection_vars(&[("TERM", "xterm-256color")], || {
+            let _guard = push_override(ColorSupportLevel::Colors256);
+            let detection = Detection::detect(
+                Stream::Stdout,
+                OutputStreamOptions::new(Some(true), None),
+                &[String::from("program_name"), String::from("--color")],
+            );
+            let json = serde_json::to_value(&detection).unwrap();
+
+            assert_eq!(json["level"], "Colors256");
+            assert_eq!(json["reason"], "override");
+            assert_eq!(json["forced"], true);
+            assert_eq!(json["stream"], "stdout");
+            assert_eq!(json["flags"], serde_json::json!(["--color"]));
+            assert_eq!(json["env"]["TERM"], "xterm-256color");
+            assert!(json["env"].get("NO_COLOR").is_none());
+        });
+    }
+}
@@ -24,10 +24,17 @@
 //! information from environment variables and command-line flags.
 //!
 
+use std::collections::HashMap;
+
 use crate::colors::ColorSupportLevel;
-use os_info;
+use crate::options::DetectionConfig;
 use regex::Regex;
 
+/// Terminal programs (`TERM_PROGRAM` values) known to support truecolor on every platform,
+/// lowercased so they can be matched case-insensitively.
+const TRUECOLOR_TERM_PROGRAMS: [&str; 5] =
+    ["vscode", "wezterm", "terminus", "waveterm", "xterm.js"];
+
 /// Struct representing the environment details.
 pub struct Environment {
     /// Terminal type.
@@ -44,6 +51,46 @@ pub struct Environment {
     pub term_program: Option<String>,
     /// Terminal program version.
     pub term_program_version: String,
+    /// Azure Pipelines agent name, read from the `AGENT_NAME` environment variable.
+    pub agent_name: Option<String>,
+    /// DomTerm session marker, read from the `DOMTERM` environment variable.
+    pub domterm: Option<String>,
+    /// `COLORTERM` value forwarded from the client over SSH, read from `LC_COLORTERM`.
+    ///
+    /// Chained SSH sessions often reset `TERM` to a conservative value and drop `COLORTERM`.
+    /// Since most servers only accept `LANG` and `LC_*` variables, clients can forward their
+    /// `COLORTERM` as `LC_COLORTERM` (e.g. `SendEnv LC_COLORTERM`). It is only consulted when
+    /// `trust_remote_colorterm` is set.
+    pub forwarded_colorterm: Option<String>,
+    /// Whether a forwarded `COLORTERM` of `truecolor` or `24bit` may enable truecolor. Defaults to
+    /// `false`, and is set from `DetectionConfig::trust_remote_colorterm` by `with_config`.
+    pub trust_remote_colorterm: bool,
+    /// Windows Terminal session identifier, read from the `WT_SESSION` environment variable.
+    pub wt_session: Option<String>,
+    /// xterm version string, such as `XTerm(370)`, read from the `XTERM_VERSION` environment
+    /// variable that real xterm exports.
+    pub xterm_version: Option<String>,
+    /// Kitty window identifier, read from the `KITTY_WINDOW_ID` environment variable.
+    pub kitty_window_id: Option<String>,
+    /// Whether running in a browser-based cloud shell (Google Cloud Shell, AWS CloudShell or Azure
+    /// Cloud Shell), whose xterm.js frontend supports truecolor.
+    pub cloud_shell: bool,
+    /// Per-`TERM` levels that override the classification, read from the
+    /// `TERM_COLOR_SUPPORT_MAP` environment variable, e.g. `xterm=2,screen=1`. Lets misdetected
+    /// terminals be fixed without code changes; malformed entries are ignored.
+    pub term_level_overrides: HashMap<String, ColorSupportLevel>,
+    /// Whether PuTTY (`TERM=putty` or `putty-256color`) is assumed to support truecolor, as
+    /// PuTTY 0.71 and later do. PuTTY does not export its version, so this is opt-in and
+    /// defaults to `false`, classifying PuTTY as 256 colors. Set from
+    /// `DetectionConfig::putty_truecolor` by `with_config`.
+    pub putty_truecolor: bool,
+    /// Color support level advertised by the terminfo entry for `term`. Only read from the
+    /// terminfo database when the `terminfo` feature is enabled, and `None` otherwise.
+    pub terminfo_level: Option<ColorSupportLevel>,
+    /// Whether the terminfo capability caps the level derived from `COLORTERM`, since terminfo is
+    /// often more authoritative for actual rendering. Defaults to `false`, and is set from
+    /// `DetectionConfig::prefer_terminfo` by `with_config`.
+    pub prefer_terminfo: bool,
 }
 
 impl Environment {
@@ -56,27 +103,102 @@ impl Environment {
         term_program: Option<String>,
         term_program_version: Option<String>,
     ) -> Self {
-        let binding = os_info::get();
-        let os_release = os_release.unwrap_or_else(|| binding.version().to_string());
+        let env = |name: &str| {
+            let provided = match name {
+                "TERM" => &term,
+                "COLORTERM" => &colorterm,
+                "TEAMCITY_VERSION" => &teamcity_version,
+                "CI" => &ci,
+                "TERM_PROGRAM" => &term_program,
+                "TERM_PROGRAM_VERSION" => &term_program_version,
+                _ => &None,
+            };
+            provided.clone().or_else(|| std::env::var(name).ok())
+        };
+        Self::from_vars(&env, os_release.unwrap_or_else(current_os_release))
+    }
+
+    /// Creates an `Environment` from the variables looked up through `env` instead of the process
+    /// environment, e.g. the environment of a child process or a fixed map in tests.
+    pub fn from_env(env: impl Fn(&str) -> Option<String>) -> Self {
+        Self::from_vars(&env, current_os_release())
+    }
+
+    /// Creates an `Environment` from the variables looked up through `env` and the given operating
+    /// system release.
+    fn from_vars(env: &dyn Fn(&str) -> Option<String>, os_release: String) -> Self {
+        let term = normalize_term(&env("TERM").unwrap_or_default());
+
+        #[cfg(feature = "terminfo")]
+        let terminfo_level = read_terminfo_level(&term);
+        #[cfg(not(feature = "terminfo"))]
+        let terminfo_level = None;
 
         Self {
-            term: term
-                .unwrap_or_else(|| std::env::var("TERM").unwrap_or_else(|_| String::from(""))),
-            colorterm: colorterm.or_else(|| std::env::var("COLORTERM").ok()),
-            teamcity_version: teamcity_version.or_else(|| std::env::var("TEAMCITY_VERSION").ok()),
-            ci: ci.or_else(|| std::env::var("CI").ok()),
+            term,
+            colorterm: env("COLORTERM"),
+            teamcity_version: env("TEAMCITY_VERSION"),
+            ci: env("CI"),
             os_release,
-            term_program: term_program.or_else(|| std::env::var("TERM_PROGRAM").ok()),
-            term_program_version: term_program_version.unwrap_or_else(|| {
-                std::env::var("TERM_PROGRAM_VERSION").unwrap_or_else(|_| String::from(""))
-            }),
+            term_program: env("TERM_PROGRAM"),
+            term_program_version: env("TERM_PROGRAM_VERSION").unwrap_or_default(),
+            agent_name: env("AGENT_NAME"),
+            domterm: env("DOMTERM"),
+            forwarded_colorterm: env("LC_COLORTERM"),
+            trust_remote_colorterm: false,
+            wt_session: env("WT_SESSION"),
+            xterm_version: env("XTERM_VERSION"),
+            kitty_window_id: env("KITTY_WINDOW_ID"),
+            cloud_shell: is_cloud_shell_env(env),
+            putty_truecolor: false,
+            term_level_overrides: env("TERM_COLOR_SUPPORT_MAP")
+                .map(|map| parse_term_level_overrides(&map))
+                .unwrap_or_default(),
+            terminfo_level,
+            prefer_terminfo: false,
         }
     }
 
+    /// Creates an `Environment` for `term` with every other signal unset, without reading the
+    /// process environment.
+    pub(crate) fn with_term(term: &str) -> Self {
+        Self {
+            term: normalize_term(term),
+            colorterm: None,
+            teamcity_version: None,
+            ci: None,
+            os_release: String::new(),
+            term_program: None,
+            term_program_version: String::new(),
+            agent_name: None,
+            domterm: None,
+            forwarded_colorterm: None,
+            trust_remote_colorterm: false,
+            wt_session: None,
+            xterm_version: None,
+            kitty_window_id: None,
+            cloud_shell: false,
+            putty_truecolor: false,
+            term_level_overrides: HashMap::new(),
+            terminfo_level: None,
+            prefer_terminfo: false,
+        }
+    }
+
+    /// Creates an `Environment` populated entirely from the process environment.
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Self {
         Self::new(None, None, None, None, None, None, None)
     }
 
+    /// Applies the classification settings of a `DetectionConfig`, such as `putty_truecolor`.
+    pub fn with_config(mut self, config: &DetectionConfig) -> Self {
+        self.trust_remote_colorterm = config.trust_remote_colorterm;
+        self.putty_truecolor = config.putty_truecolor;
+        self.prefer_terminfo = config.prefer_terminfo;
+        self
+    }
+
     /// Gets the parts of the OS release version.
     fn get_os_release_parts(&self) -> Vec<u32> {
         self.os_release
@@ -95,29 +217,159 @@ impl Environment {
         None
     }
 
+    /// Returns `true` if the terminal name denotes an ANSI-capable terminal, such as `ansi`,
+    /// `iris-ansi`, `pcansi`, or the DOS and OS/2 console sizes `ansi80x25` and `ansi80x50`.
+    /// Only whole `-`-separated segments that start or end with `ansi` count, and monochrome
+    /// variants like `ansi-m` or `ansi-mono` are excluded.
+    fn is_ansi_term(&self) -> bool {
+        let has_ansi_segment = self
+            .term
+            .split('-')
+            .any(|segment| segment.starts_with("ansi") || segment.ends_with("ansi"));
+        has_ansi_segment && !self.is_monochrome_variant()
+    }
+
+    /// Returns `true` if the terminal name carries a monochrome `-m` or `-mono` suffix segment.
+    fn is_monochrome_variant(&self) -> bool {
+        self.term
+            .split('-')
+            .skip(1)
+            .any(|segment| segment == "m" || segment == "mono")
+    }
+
+    /// Returns `true` if the terminal is the FreeBSD syscons console (`cons25`, `cons50`,
+    /// `cons25l1`, ...), excluding its monochrome variants.
+    fn is_bsd_console(&self) -> bool {
+        self.term
+            .strip_prefix("cons")
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            && !self.is_monochrome_variant()
+    }
+
+    /// Caps a level derived from `COLORTERM` to the terminfo capability when `prefer_terminfo` is
+    /// set and the capability is known.
+    fn cap_colorterm_level(&self, level: ColorSupportLevel) -> ColorSupportLevel {
+        match self.terminfo_level {
+            Some(terminfo_level) if self.prefer_terminfo => level.min(terminfo_level),
+            _ => level,
+        }
+    }
+
+    /// Determines the color support level implied by a known terminal program, if any.
+    fn term_program_color_level(&self) -> Option<ColorSupportLevel> {
+        let term_program = self.term_program.as_deref()?.to_lowercase();
+
+        if TRUECOLOR_TERM_PROGRAMS.contains(&term_program.as_str()) {
+            return Some(ColorSupportLevel::TrueColor);
+        }
+
+        // Without a version, a known program still supports its minimum level.
+        match term_program.as_str() {
+            "iterm.app" => Some(match self.get_term_program_version_major() {
+                Some(version_major) if version_major >= 3 => ColorSupportLevel::TrueColor,
+                _ => ColorSupportLevel::Colors256,
+            }),
+            "apple_terminal" => Some(ColorSupportLevel::Colors256),
+            _ => None,
+        }
+    }
+
+    /// Gets the xterm patch number from `XTERM_VERSION`, e.g. `370` for `XTerm(370)`.
+    fn get_xterm_patch(&self) -> Option<u32> {
+        self.xterm_version
+            .as_deref()?
+            .strip_prefix("XTerm(")?
+            .strip_suffix(')')?
+            .parse()
+            .ok()
+    }
+
+    /// Returns `true` if the terminal is a known 8-color terminal that only renders the upper 8
+    /// ("bright") colors through the bold attribute, such as the Linux console.
+    pub fn bright_via_bold(&self) -> bool {
+        matches!(self.term.as_str(), "linux" | "cons25" | "cons50")
+    }
+
     /// Determines the color support level based on the environment.
     pub fn determine_color_level(&self) -> ColorSupportLevel {
+        self.determine_color_level_with_certainty().0
+    }
+
+    /// Determines the color support level based on the environment, together with whether it
+    /// comes from an authoritative signal, such as `COLORTERM=truecolor`, a known terminal program
+    /// or a terminfo entry advertising direct color, rather than a guess from the `TERM` name.
+    pub fn determine_color_level_with_certainty(&self) -> (ColorSupportLevel, bool) {
+        let (level, certain) = self.classify_color_level();
+        // Only a terminfo entry advertising direct color (`Tc`, `RGB` or 16M `colors`) is
+        // authoritative; a matching `colors` count is as much a guess as the `TERM` name.
+        let direct_color = level == ColorSupportLevel::TrueColor
+            && self.terminfo_level == Some(ColorSupportLevel::TrueColor);
+        (level, certain || direct_color)
+    }
+
+    /// Classifies the color support level and whether it is certain, including the operating
+    /// system specific logic.
+    fn classify_color_level(&self) -> (ColorSupportLevel, bool) {
+        if let Some(&level) = self.term_level_overrides.get(&self.term) {
+            return (level, true);
+        }
+
         if self.term == "dumb" {
-            return ColorSupportLevel::NoColor;
+            return (ColorSupportLevel::NoColor, true);
         }
 
+        // The PowerShell ISE exports no environment signal that tells it apart from the console
+        // host (`PSModulePath` is set system-wide), but it runs native commands without a console,
+        // so their streams are never terminals and resolve to `NoColor` before this.
         if cfg!(windows) {
+            // Explicit terminal signals win over the OS build, which can under-report modern
+            // terminals such as Windows Terminal.
+            if self.colorterm.as_deref() == Some("truecolor") {
+                return (self.cap_colorterm_level(ColorSupportLevel::TrueColor), true);
+            }
+            if self.wt_session.is_some() {
+                return (ColorSupportLevel::TrueColor, true);
+            }
+            if let Some(level) = self.term_program_color_level() {
+                return (level, true);
+            }
+
             let release_parts = self.get_os_release_parts();
-            if release_parts[0] >= 10 && release_parts[2] >= 10_586 {
-                return if release_parts[2] >= 14_931 {
-                    ColorSupportLevel::TrueColor
+            let major = release_parts.first().copied().unwrap_or(0);
+            let build = release_parts.get(2).copied().unwrap_or(0);
+            if major >= 10 && build >= 10_586 {
+                return if build >= 14_931 {
+                    (ColorSupportLevel::TrueColor, false)
                 } else {
-                    ColorSupportLevel::Colors256
+                    (ColorSupportLevel::Colors256, false)
                 };
             }
-            return ColorSupportLevel::Basic;
+            return (ColorSupportLevel::Basic, false);
+        }
+
+        // Every other target, including non-mainstream ones such as Redox, relies on the
+        // TERM/COLORTERM heuristics alone.
+        self.classify_terminal_color_level()
+    }
+
+    /// Determines the color support level from the terminal-related signals only, without any
+    /// operating system specific logic.
+    pub(crate) fn determine_terminal_color_level(&self) -> ColorSupportLevel {
+        self.classify_terminal_color_level().0
+    }
+
+    /// Classifies the color support level from the terminal-related signals only, together with
+    /// whether it comes from an authoritative signal rather than a guess.
+    fn classify_terminal_color_level(&self) -> (ColorSupportLevel, bool) {
+        if self.term == "dumb" {
+            return (ColorSupportLevel::NoColor, true);
         }
 
         if let Some(ci) = &self.ci {
-            if ci == "TF_BUILD" && std::env::var("AGENT_NAME").is_ok() {
-                return ColorSupportLevel::Basic;
+            if ci == "TF_BUILD" && self.agent_name.is_some() {
+                return (ColorSupportLevel::Basic, false);
             }
-            return ColorSupportLevel::NoColor;
+            return (ColorSupportLevel::NoColor, false);
         }
 
         let regex = Regex::new(r#"^(9\.(0*[1-9]\d*|0+)|\d{2,}\.)"#).unwrap();
@@ -125,42 +377,80 @@ impl Environment {
         // Check if teamcity_version exists and matches the regex pattern
         if let Some(teamcity_version) = &self.teamcity_version {
             if regex.is_match(teamcity_version) {
-                return ColorSupportLevel::Basic;
+                return (ColorSupportLevel::Basic, false);
             } else {
-                return ColorSupportLevel::NoColor;
+                return (ColorSupportLevel::NoColor, false);
             }
         }
 
         if let Some(colorterm) = &self.colorterm {
             if colorterm == "truecolor" {
-                return ColorSupportLevel::TrueColor;
+                return (self.cap_colorterm_level(ColorSupportLevel::TrueColor), true);
             }
         }
 
-        if self.term == "xterm-kitty" {
-            return ColorSupportLevel::TrueColor;
-        }
-
-        if let Some(term_program) = &self.term_program {
-            if let Some(version_major) = self.get_term_program_version_major() {
-                match term_program.as_str() {
-                    "iTerm.app" => {
-                        return if version_major >= 3 {
-                            ColorSupportLevel::TrueColor
-                        } else {
-                            ColorSupportLevel::Colors256
-                        };
-                    }
-                    "Apple_Terminal" => {
-                        return ColorSupportLevel::Colors256;
-                    }
-                    _ => {}
+        if self.trust_remote_colorterm {
+            if let Some(forwarded_colorterm) = &self.forwarded_colorterm {
+                if forwarded_colorterm == "truecolor" || forwarded_colorterm == "24bit" {
+                    return (self.cap_colorterm_level(ColorSupportLevel::TrueColor), true);
                 }
             }
         }
 
-        if self.term.ends_with("-256color") {
-            return ColorSupportLevel::Colors256;
+        // Kitty always supports truecolor, even when TERM was changed (e.g. over SSH or inside
+        // tmux), and sets `KITTY_WINDOW_ID` in every window.
+        if self.term == "xterm-kitty" || self.kitty_window_id.is_some() {
+            return (ColorSupportLevel::TrueColor, true);
+        }
+
+        // DomTerm reports `TERM=xterm-256color` but supports truecolor.
+        if self.domterm.is_some() {
+            return (ColorSupportLevel::TrueColor, true);
+        }
+
+        // Cloud shells report `TERM=xterm-256color`, but their xterm.js frontend supports truecolor.
+        if self.cloud_shell {
+            return (ColorSupportLevel::TrueColor, true);
+        }
+
+        if let Some(level) = self.term_program_color_level() {
+            return (level, true);
+        }
+
+        // PuTTY supports at least 256 colors, even when it reports plain `TERM=putty`.
+        if self.term == "putty" || self.term == "putty-256color" {
+            return if self.putty_truecolor {
+                (ColorSupportLevel::TrueColor, false)
+            } else {
+                (ColorSupportLevel::Colors256, false)
+            };
+        }
+
+        // 88-color terminals are closest to 256 colors.
+        if self.term.ends_with("-256color") || self.term.ends_with("-88color") {
+            return (ColorSupportLevel::Colors256, false);
+        }
+
+        if self.term.ends_with("-16color") {
+            return (ColorSupportLevel::Basic, false);
+        }
+
+        // macOS NSTerminal variants (`nsterm`, `nsterm-build309`, ...) support at least 256 colors,
+        // except the monochrome `-m` entries.
+        if self.term.starts_with("nsterm") && !self.is_monochrome_variant() {
+            return (ColorSupportLevel::Colors256, false);
+        }
+
+        // Real xterm reports plain `TERM=xterm` but supports 256 colors, and truecolor since
+        // patch 331.
+        if self.term == "xterm" {
+            if let Some(patch) = self.get_xterm_patch() {
+                return if patch >= 331 {
+                    (ColorSupportLevel::TrueColor, true)
+                } else {
+                    (ColorSupportLevel::Colors256, true)
+                };
+            }
         }
 
         if self.term.starts_with("screen")
@@ -169,91 +459,192 @@ impl Environment {
             || self.term.starts_with("vt220")
             || self.term.starts_with("rxvt")
             || self.term.contains("color")
-            || self.term.contains("ansi")
+            || self.is_ansi_term()
+            || self.is_bsd_console()
+            || self.term.starts_with("teken")
+            // Framebuffer terminals: jfbterm (Japanese) and bterm (BOGL).
+            || self.term == "jfbterm"
+            || self.term == "bterm"
             || self.term.contains("cygwin")
             || self.term.contains("linux")
         {
-            return ColorSupportLevel::Basic;
+            return (ColorSupportLevel::Basic, false);
         }
 
         if let Some(colorterm) = &self.colorterm {
             if !colorterm.is_empty() {
-                return ColorSupportLevel::Basic;
+                return (ColorSupportLevel::Basic, false);
             }
         }
 
-        ColorSupportLevel::NoColor
+        (ColorSupportLevel::NoColor, false)
+    }
+}
+
+/// Returns the Windows release reported by `os_info`, whose build decides the color level.
+#[cfg(windows)]
+fn current_os_release() -> String {
+    os_info::get().version().to_string()
+}
+
+/// Returns an empty release: only Windows consults the release, so other targets, including
+/// non-mainstream ones such as Redox, never query the operating system.
+#[cfg(not(windows))]
+fn current_os_release() -> String {
+    String::new()
+}
+
+/// Checks the marker variables of browser-based cloud shells: `CLOUD_SHELL=true` (Google Cloud
+/// Shell), `AWS_EXECUTION_ENV=CloudShell` (AWS CloudShell), and `ACC_CLOUD` or
+/// `AZUREPS_HOST_ENVIRONMENT=cloud-shell/...` (Azure Cloud Shell).
+fn is_cloud_shell_env(env: &dyn Fn(&str) -> Option<String>) -> bool {
+    env("CLOUD_SHELL").is_some_and(|value| value == "true")
+        || env("AWS_EXECUTION_ENV").is_some_and(|value| value == "CloudShell")
+        || env("ACC_CLOUD").is_some()
+        || env("AZUREPS_HOST_ENVIRONMENT").is_some_and(|value| value.starts_with("cloud-shell"))
+}
+
+/// Parses a `TERM_COLOR_SUPPORT_MAP` value of comma-separated `TERM=level` entries, such as
+/// `xterm=2,screen=basic`. Entries without a `TERM`, without `=`, or with an unrecognized level
+/// are ignored.
+fn parse_term_level_overrides(map: &str) -> HashMap<String, ColorSupportLevel> {
+    map.split(',')
+        .filter_map(|entry| {
+            let (term, level) = entry.split_once('=')?;
+            let term = normalize_term(term);
+            if term.is_empty() {
+                return None;
+            }
+            Some((term, level.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Normalizes a `TERM` value from a misconfigured shell by dropping control characters and
+/// surrounding whitespace, so prefix and suffix matching still works.
+pub(crate) fn normalize_term(term: &str) -> String {
+    term.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Reads the color support level advertised by the terminfo entry for `term`, from its `Tc`/`RGB`
+/// truecolor flags or its `colors` capability.
+#[cfg(feature = "terminfo")]
+fn read_terminfo_level(term: &str) -> Option<ColorSupportLevel> {
+    let database = terminfo::Database::from_name(term).ok()?;
+    if database.raw("Tc").is_some() || database.raw("RGB").is_some() {
+        return Some(ColorSupportLevel::TrueColor);
     }
+
+    let max_colors: i32 = database.get::<terminfo::capability::MaxColors>()?.into();
+    Some(match max_colors {
+        colors if colors >= 16_777_216 => ColorSupportLevel::TrueColor,
+        colors if colors >= 256 => ColorSupportLevel::Colors256,
+        colors if colors >= 8 => ColorSupportLevel::Basic,
+        _ => ColorSupportLevel::NoColor,
+    })
+}
+
+/// Classifies the color support level implied by the given terminal signals alone.
+///
+/// This is a pure function: it assumes the stream is a TTY, ignores overrides, CI detection and
+/// operating system specifics, and never reads the process environment. It answers "what does this
+/// `TERM` mean?".
+pub fn classify(
+    term: &str,
+    colorterm: Option<&str>,
+    term_program: Option<&str>,
+) -> ColorSupportLevel {
+    let mut environment = Environment::with_term(term);
+    environment.colorterm = colorterm.map(String::from);
+    environment.term_program = term_program.map(String::from);
+    environment.determine_terminal_color_level()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::detection::with_detection_vars;
+
+    /// Creates an environment for `term` with every other terminal signal cleared, so the result
+    /// does not depend on the host environment.
+    fn environment_with_term(term: &str) -> Environment {
+        let mut environment = Environment::with_term(term);
+        environment.os_release = current_os_release();
+        environment
+    }
 
     // Test determine_color_level() under various conditions
     #[test]
+    #[cfg(not(windows))]
     fn test_determine_color_level() {
-        // Test when term is "dumb"
-        let mut environment_dumb = Environment::default();
-        environment_dumb.term = String::from("dumb");
-        assert_eq!(
-            environment_dumb.determine_color_level(),
-            ColorSupportLevel::NoColor
-        );
+        // Clear the host's terminal signals, such as `TERM_PROGRAM`, so only `TERM` is tested.
+        with_detection_vars(&[], || {
+            // Test when term is "dumb"
+            let mut environment_dumb = Environment::default();
+            environment_dumb.term = String::from("dumb");
+            assert_eq!(
+                environment_dumb.determine_color_level(),
+                ColorSupportLevel::NoColor
+            );
 
-        // Test when term is "xterm-kitty"
-        let mut environment_xterm_kitty = Environment::default();
-        environment_xterm_kitty.term = String::from("xterm-kitty");
-        assert_eq!(
-            environment_xterm_kitty.determine_color_level(),
-            ColorSupportLevel::TrueColor
-        );
+            // Test when term is "xterm-kitty"
+            let mut environment_xterm_kitty = Environment::default();
+            environment_xterm_kitty.term = String::from("xterm-kitty");
+            assert_eq!(
+                environment_xterm_kitty.determine_color_level(),
+                ColorSupportLevel::TrueColor
+            );
 
-        // Test when term starts with "vt100"
-        let environment_vt100 = Environment::new(
-            Some(String::from("vt100-color")),
-            Some(String::from("")),
-            None,
-            None,
-            None,
-            None,
-            None,
-        );
-        assert_eq!(
-            environment_vt100.determine_color_level(),
-            ColorSupportLevel::Basic
-        );
+            // Test when term starts with "vt100"
+            let environment_vt100 = Environment::new(
+                Some(String::from("vt100-color")),
+                Some(String::from("")),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            assert_eq!(
+                environment_vt100.determine_color_level(),
+                ColorSupportLevel::Basic
+            );
 
-        // Test when term is "screen" and colorterm is "truecolor"
-        let environment_screen_truecolor = Environment::new(
-            Some(String::from("screen")),
-            Some(String::from("truecolor")),
-            None,
-            None,
-            None,
-            None,
-            None,
-        );
-        assert_eq!(
-            environment_screen_truecolor.determine_color_level(),
-            ColorSupportLevel::TrueColor
-        );
+            // Test when term is "screen" and colorterm is "truecolor"
+            let environment_screen_truecolor = Environment::new(
+                Some(String::from("screen")),
+                Some(String::from("truecolor")),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            assert_eq!(
+                environment_screen_truecolor.determine_color_level(),
+                ColorSupportLevel::TrueColor
+            );
 
-        // Test when term is "linux"
-        let mut environment_linux = Environment::new(
-            Some(String::from("linux")),
-            Some(String::from("")),
-            None,
-            None,
-            None,
-            None,
-            None,
-        );
-        environment_linux.term = String::from("linux");
-        assert_eq!(
-            environment_linux.determine_color_level(),
-            ColorSupportLevel::Basic
-        );
+            // Test when term is "linux"
+            let mut environment_linux = Environment::new(
+                Some(String::from("linux")),
+                Some(String::from("")),
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            environment_linux.term = String::from("linux");
+            assert_eq!(
+                environment_linux.determine_color_level(),
+                ColorSupportLevel::Basic
+            );
+        });
     }
 
     #[test]
@@ -261,6 +652,9 @@ mod tests {
     fn test_windows_color_level() {
         // Test when release_parts[0] < 10
         let mut environment = Environment::default();
+        environment.colorterm = None;
+        environment.wt_session = None;
+        environment.term_program = None;
         environment.os_release = String::from("9.0.0");
         assert_eq!(
             environment.determine_color_level(),
@@ -290,69 +684,644 @@ mod tests {
     }
 
     #[test]
-    fn test_teamcity_version() {
-        // Test when teamcity_version starts with "9."
+    #[cfg(target_os = "windows")]
+    fn test_windows_terminal_colorterm_truecolor() {
+        // Test Windows Terminal on a build that would otherwise imply 256 colors
         let mut environment = Environment::default();
-        environment.teamcity_version = Some(String::from("9.1"));
+        environment.os_release = String::from("10.0.10586");
+        environment.wt_session = Some(String::from("0b9a3e4c-1f6b-4c55-9f0e-2d7b6a1c8e11"));
+        environment.colorterm = Some(String::from("truecolor"));
         assert_eq!(
             environment.determine_color_level(),
-            ColorSupportLevel::Basic
+            ColorSupportLevel::TrueColor
         );
 
-        // Test when teamcity_version starts with a numeric character
+        // Test the same build without COLORTERM falling back to the build heuristic
+        environment.wt_session = None;
+        environment.colorterm = None;
+        environment.term_program = None;
+        assert_eq!(
+            environment.determine_color_level(),
+            ColorSupportLevel::Colors256
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_windows_terminal_signals_precede_os_build() {
+        // Test WT_SESSION alone on a build that would otherwise imply 256 colors
         let mut environment = Environment::default();
-        environment.teamcity_version = Some(String::from("10.0"));
+        environment.os_release = String::from("10.0.10586");
+        environment.colorterm = None;
+        environment.term_program = None;
+        environment.wt_session = Some(String::from("session"));
+        assert_eq!(
+            environment.determine_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+
+        // Test a known truecolor terminal program on a build that would imply basic color
+        environment.os_release = String::from("10.0.0");
+        environment.wt_session = None;
+        environment.term_program = Some(String::from("vscode"));
+        assert_eq!(
+            environment.determine_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+
+        // Test the OS build heuristic once no explicit signal is present
+        environment.term_program = None;
         assert_eq!(
             environment.determine_color_level(),
             ColorSupportLevel::Basic
         );
 
-        // Test when teamcity_version does not meet the conditions
-        let mut environment = Environment::default();
-        environment.teamcity_version = Some(String::from("8.0"));
+        // Test a short OS release does not panic
+        environment.os_release = String::from("10");
         assert_eq!(
             environment.determine_color_level(),
-            ColorSupportLevel::NoColor
+            ColorSupportLevel::Basic
         );
     }
 
+    #[test]
+    fn test_known_truecolor_term_programs() {
+        for term_program in ["vscode", "WezTerm", "wezterm", "Terminus", "waveterm"] {
+            let mut environment = environment_with_term("xterm");
+            environment.term_program = Some(String::from(term_program));
+            assert_eq!(
+                environment.determine_color_level(),
+                ColorSupportLevel::TrueColor,
+                "TERM_PROGRAM={term_program}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wt_session_read_from_env() {
+        with_detection_vars(&[("WT_SESSION", "session")], || {
+            let environment = Environment::default();
+            assert_eq!(environment.wt_session, Some(String::from("session")));
+        });
+    }
+
+    #[test]
+    fn test_teamcity_version() {
+        with_detection_vars(&[], || {
+            // Test when teamcity_version starts with "9."
+            let mut environment = Environment::default();
+            environment.teamcity_version = Some(String::from("9.1"));
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::Basic
+            );
+
+            // Test when teamcity_version starts with a numeric character
+            let mut environment = Environment::default();
+            environment.teamcity_version = Some(String::from("10.0"));
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::Basic
+            );
+
+            // Test when teamcity_version does not meet the conditions
+            let mut environment = Environment::default();
+            environment.teamcity_version = Some(String::from("8.0"));
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::NoColor
+            );
+        });
+    }
+
     #[test]
     fn test_get_term_program_version_major() {
-        let mut environment = Environment::default();
-        environment.term_program_version = String::from("3.2.1");
-        assert_eq!(environment.get_term_program_version_major(), Some(3));
+        with_detection_vars(&[], || {
+            let mut environment = Environment::default();
+            environment.term_program_version = String::from("3.2.1");
+            assert_eq!(environment.get_term_program_version_major(), Some(3));
 
-        let mut environment = Environment::default();
-        environment.colorterm = Some(String::from(""));
-        environment.term_program = Some(String::from("Apple_Terminal"));
+            let mut environment = Environment::default();
+            environment.term = String::from("xterm");
+            environment.ci = None;
+            environment.colorterm = Some(String::from(""));
+            environment.term_program = Some(String::from("Apple_Terminal"));
+            environment.term_program_version = String::from("440");
+            assert_eq!(
+                environment.determine_color_level(),
+                ColorSupportLevel::Colors256
+            );
+
+            let mut environment: Environment = Environment::default();
+            environment.term = String::from("xterm");
+            environment.ci = None;
+            environment.term_program_version = String::from("3.2.1");
+            environment.colorterm = Some(String::from(""));
+            environment.term_program = Some(String::from("iTerm.app"));
+            assert_eq!(
+                environment.determine_color_level(),
+                ColorSupportLevel::TrueColor
+            );
+
+            let mut environment: Environment = Environment::default();
+            environment.term = String::from("xterm");
+            environment.ci = None;
+            environment.colorterm = Some(String::from(""));
+            environment.term_program = Some(String::from("iTerm.app"));
+            environment.term_program_version = String::from("2.2.1");
+            assert_eq!(
+                environment.determine_color_level(),
+                ColorSupportLevel::Colors256
+            );
+        });
+    }
+
+    #[test]
+    fn test_domterm() {
+        with_detection_vars(
+            &[
+                ("DOMTERM", "QT;version=3.0;tty=/dev/pts/1"),
+                ("TERM", "xterm-256color"),
+            ],
+            || {
+                let environment = Environment::default();
+                assert_eq!(
+                    environment.determine_color_level(),
+                    ColorSupportLevel::TrueColor
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn test_term_program_case_insensitive() {
+        for term_program in ["iterm.app", "ITERM.APP", "iTerm.App"] {
+            let mut environment = environment_with_term("xterm");
+            environment.term_program = Some(String::from(term_program));
+            environment.term_program_version = String::from("3.4.0");
+            assert_eq!(
+                environment.determine_color_level(),
+                ColorSupportLevel::TrueColor
+            );
+        }
+
+        for term_program in ["apple_terminal", "APPLE_TERMINAL", "Apple_terminal"] {
+            let mut environment = environment_with_term("xterm");
+            environment.term_program = Some(String::from(term_program));
+            environment.term_program_version = String::from("440");
+            assert_eq!(
+                environment.determine_color_level(),
+                ColorSupportLevel::Colors256
+            );
+        }
+    }
+
+    #[test]
+    fn test_forwarded_colorterm_requires_trust() {
+        let mut environment = environment_with_term("xterm");
+        environment.forwarded_colorterm = Some(String::from("truecolor"));
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+
+        environment.trust_remote_colorterm = true;
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+    }
+
+    #[test]
+    fn test_forwarded_colorterm_non_truecolor_value() {
+        let mut environment = environment_with_term("xterm");
+        environment.forwarded_colorterm = Some(String::from("yes"));
+        environment.trust_remote_colorterm = true;
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+    }
+
+    #[test]
+    fn test_forwarded_colorterm_read_from_env() {
+        with_detection_vars(&[("LC_COLORTERM", "24bit")], || {
+            let environment = Environment::default();
+            assert_eq!(environment.forwarded_colorterm, Some(String::from("24bit")));
+            assert!(!environment.trust_remote_colorterm);
+        });
+    }
+
+    #[test]
+    fn test_prefer_terminfo_caps_colorterm() {
+        let mut environment = environment_with_term("xterm-256color");
+        environment.colorterm = Some(String::from("truecolor"));
+        environment.terminfo_level = Some(ColorSupportLevel::Colors256);
+        assert_eq!(
+            environment.determine_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+
+        environment.prefer_terminfo = true;
         assert_eq!(
             environment.determine_color_level(),
             ColorSupportLevel::Colors256
         );
+    }
 
-        let mut environment: Environment = Environment::default();
-        environment.term_program_version = String::from("3.2.1");
-        environment.colorterm = Some(String::from(""));
-        environment.term_program = Some(String::from("iTerm.app"));
+    #[test]
+    fn test_prefer_terminfo_without_terminfo_level() {
+        let mut environment = environment_with_term("xterm-256color");
+        environment.colorterm = Some(String::from("truecolor"));
+        environment.prefer_terminfo = true;
         assert_eq!(
             environment.determine_color_level(),
             ColorSupportLevel::TrueColor
         );
+    }
+
+    #[test]
+    #[cfg(feature = "terminfo")]
+    fn test_read_terminfo_level() {
+        assert_eq!(read_terminfo_level("no-such-terminal"), None);
+        // The terminfo database may not be installed, so only check entries that are found.
+        if let Some(level) = read_terminfo_level("xterm-256color") {
+            assert!(level >= ColorSupportLevel::Colors256);
+        }
+    }
+
+    #[test]
+    fn test_bright_via_bold() {
+        let mut environment = Environment::default();
+        environment.term = String::from("linux");
+        assert!(environment.bright_via_bold());
 
-        let mut environment: Environment = Environment::default();
-        environment.colorterm = Some(String::from(""));
-        environment.term_program_version = String::from("2.2.1");
+        // The FreeBSD syscons console renders bright colors through bold too.
+        environment.term = String::from("cons25");
+        assert!(environment.bright_via_bold());
+        environment.term = String::from("cons50");
+        assert!(environment.bright_via_bold());
+
+        environment.term = String::from("xterm-256color");
+        assert!(!environment.bright_via_bold());
+    }
+
+    #[test]
+    fn test_vendor_ansi_terminals() {
+        assert_eq!(
+            environment_with_term("iris-ansi").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("ansi").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("pcansi").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+    }
+
+    #[test]
+    fn test_parse_term_level_overrides() {
+        let overrides = parse_term_level_overrides("xterm=2,screen=1");
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides["xterm"], ColorSupportLevel::Colors256);
+        assert_eq!(overrides["screen"], ColorSupportLevel::Basic);
+
+        let overrides =
+            parse_term_level_overrides(" xterm = truecolor ,screen,=1,linux=9,,vt100=0=1,rxvt=0");
+        assert_eq!(overrides.len(), 2);
+        assert_eq!(overrides["xterm"], ColorSupportLevel::TrueColor);
+        assert_eq!(overrides["rxvt"], ColorSupportLevel::NoColor);
+    }
+
+    #[test]
+    fn test_term_level_overrides() {
+        let mut environment = environment_with_term("xterm");
+        environment.term_level_overrides = parse_term_level_overrides("xterm=2,screen=bogus");
         assert_eq!(
             environment.determine_color_level(),
             ColorSupportLevel::Colors256
         );
+
+        let mut environment = environment_with_term("screen");
+        environment.term_level_overrides = parse_term_level_overrides("xterm=2,screen=bogus");
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
     }
 
     #[test]
-    fn test_determine_color_level_basic() {
-        let mut environment = Environment::default();
-        environment.colorterm = Some(String::from(""));
-        environment.term = String::from("rxvt");
+    fn test_term_level_overrides_from_env() {
+        with_detection_vars(&[("TERM_COLOR_SUPPORT_MAP", "xterm=3,screen=x")], || {
+            let environment = Environment::default();
+            assert_eq!(environment.term_level_overrides.len(), 1);
+            assert_eq!(
+                environment.term_level_overrides["xterm"],
+                ColorSupportLevel::TrueColor
+            );
+        });
+    }
+
+    #[test]
+    fn test_kitty_window_id() {
+        for term in [
+            "xterm-kitty",
+            "xterm-256color",
+            "screen",
+            "linux",
+            "unknown",
+            "",
+        ] {
+            let mut environment = environment_with_term(term);
+            environment.kitty_window_id = Some(String::from("1"));
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::TrueColor,
+                "TERM={:?}",
+                term
+            );
+        }
+    }
+
+    #[test]
+    fn test_determine_color_level_with_certainty() {
+        let mut environment = environment_with_term("xterm-256color");
+        assert_eq!(
+            environment.classify_terminal_color_level(),
+            (ColorSupportLevel::Colors256, false)
+        );
+
+        environment.colorterm = Some(String::from("truecolor"));
+        assert_eq!(
+            environment.classify_terminal_color_level(),
+            (ColorSupportLevel::TrueColor, true)
+        );
+
+        environment.colorterm = None;
+        environment.term_program = Some(String::from("WezTerm"));
+        assert_eq!(
+            environment.classify_terminal_color_level(),
+            (ColorSupportLevel::TrueColor, true)
+        );
+
+        let mut environment = environment_with_term("dumb");
+        environment.term_level_overrides = parse_term_level_overrides("dumb=1");
+        assert_eq!(
+            environment.determine_color_level_with_certainty(),
+            (ColorSupportLevel::Basic, true)
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_only_direct_color_terminfo_confirms_certainty() {
+        let mut environment = environment_with_term("xterm-256color");
+        environment.terminfo_level = Some(ColorSupportLevel::Colors256);
+        assert_eq!(
+            environment.determine_color_level_with_certainty(),
+            (ColorSupportLevel::Colors256, false)
+        );
+
+        environment.terminfo_level = Some(ColorSupportLevel::Basic);
+        assert_eq!(
+            environment.determine_color_level_with_certainty(),
+            (ColorSupportLevel::Colors256, false)
+        );
+
+        // PuTTY's truecolor opt-in is a guess, which a direct color terminfo entry confirms.
+        let mut environment = environment_with_term("putty");
+        environment.putty_truecolor = true;
+        assert_eq!(
+            environment.determine_color_level_with_certainty(),
+            (ColorSupportLevel::TrueColor, false)
+        );
+
+        environment.terminfo_level = Some(ColorSupportLevel::TrueColor);
+        assert_eq!(
+            environment.determine_color_level_with_certainty(),
+            (ColorSupportLevel::TrueColor, true)
+        );
+    }
+
+    #[test]
+    fn test_xterm_js_term_program() {
+        let mut environment = environment_with_term("xterm");
+        environment.term_program = Some(String::from("xterm.js"));
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+    }
+
+    #[test]
+    fn test_cloud_shell() {
+        let mut environment = environment_with_term("xterm-256color");
+        environment.cloud_shell = true;
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+    }
+
+    #[test]
+    fn test_cloud_shell_markers() {
+        let markers = [
+            ("CLOUD_SHELL", "true"),
+            ("AWS_EXECUTION_ENV", "CloudShell"),
+            ("ACC_CLOUD", "1"),
+            ("AZUREPS_HOST_ENVIRONMENT", "cloud-shell/1.0"),
+        ];
+        for (name, value) in markers {
+            let env = |var: &str| (var == name).then(|| String::from(value));
+            assert!(is_cloud_shell_env(&env), "{}={}", name, value);
+        }
+        let env = |var: &str| match var {
+            "CLOUD_SHELL" => Some(String::from("false")),
+            "AWS_EXECUTION_ENV" => Some(String::from("AWS_Lambda_nodejs18.x")),
+            _ => None,
+        };
+        assert!(!is_cloud_shell_env(&env));
+    }
+
+    #[test]
+    fn test_from_env() {
+        let env = |name: &str| match name {
+            "TERM" => Some(String::from(" xterm-256color\n")),
+            "CI" => Some(String::from("TF_BUILD")),
+            "AGENT_NAME" => Some(String::from("mock_agent")),
+            _ => None,
+        };
+        let environment = Environment::from_env(env);
+        assert_eq!(environment.term, "xterm-256color");
+        assert_eq!(environment.colorterm, None);
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+    }
+
+    #[test]
+    fn test_putty() {
+        assert_eq!(
+            environment_with_term("putty").determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+        assert_eq!(
+            environment_with_term("putty-256color").determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+    }
+
+    #[test]
+    fn test_putty_truecolor_opt_in() {
+        for term in ["putty", "putty-256color"] {
+            let mut environment = environment_with_term(term);
+            environment.putty_truecolor = true;
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::TrueColor
+            );
+        }
+
+        let mut environment = environment_with_term("xterm-256color");
+        environment.putty_truecolor = true;
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+    }
+
+    #[test]
+    fn test_framebuffer_terminals() {
+        assert_eq!(
+            environment_with_term("jfbterm").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("bterm").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+    }
+
+    #[test]
+    fn test_dos_and_os2_ansi_terminals() {
+        assert_eq!(
+            environment_with_term("ansi80x25").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("ansi80x50").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("ansi80x25-mono").determine_terminal_color_level(),
+            ColorSupportLevel::NoColor
+        );
+    }
+
+    #[test]
+    fn test_ansi_lookalike_terminals_not_matched() {
+        assert_eq!(
+            environment_with_term("transient").determine_terminal_color_level(),
+            ColorSupportLevel::NoColor
+        );
+        assert_eq!(
+            environment_with_term("ansi-mono").determine_terminal_color_level(),
+            ColorSupportLevel::NoColor
+        );
+    }
+
+    #[test]
+    fn test_bsd_console() {
+        assert_eq!(
+            environment_with_term("cons25").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("cons50").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("cons25l1").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("cons25-m").determine_terminal_color_level(),
+            ColorSupportLevel::NoColor
+        );
+        // Only syscons names have a digit after `cons`.
+        assert_eq!(
+            environment_with_term("console").determine_terminal_color_level(),
+            ColorSupportLevel::NoColor
+        );
+        assert_eq!(
+            environment_with_term("constant").determine_terminal_color_level(),
+            ColorSupportLevel::NoColor
+        );
+    }
+
+    #[test]
+    fn test_classify_term_values() {
+        let cases = [
+            ("dumb", ColorSupportLevel::NoColor),
+            ("", ColorSupportLevel::NoColor),
+            ("unknown", ColorSupportLevel::NoColor),
+            ("xterm", ColorSupportLevel::Basic),
+            ("xterm-color", ColorSupportLevel::Basic),
+            ("xterm-256color", ColorSupportLevel::Colors256),
+            ("xterm-kitty", ColorSupportLevel::TrueColor),
+            ("screen", ColorSupportLevel::Basic),
+            ("screen-256color", ColorSupportLevel::Colors256),
+            ("tmux-256color", ColorSupportLevel::Colors256),
+            ("vt100", ColorSupportLevel::Basic),
+            ("vt220", ColorSupportLevel::Basic),
+            ("rxvt-unicode", ColorSupportLevel::Basic),
+            ("linux", ColorSupportLevel::Basic),
+            ("cygwin", ColorSupportLevel::Basic),
+            ("ansi", ColorSupportLevel::Basic),
+            ("cons25", ColorSupportLevel::Basic),
+        ];
+        for (term, expected) in cases {
+            assert_eq!(classify(term, None, None), expected, "TERM={term}");
+        }
+    }
+
+    #[test]
+    fn test_classify_colorterm_and_term_program() {
+        assert_eq!(
+            classify("xterm", Some("truecolor"), None),
+            ColorSupportLevel::TrueColor
+        );
+        assert_eq!(
+            classify("unknown", Some("yes"), None),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            classify("dumb", Some("truecolor"), None),
+            ColorSupportLevel::NoColor
+        );
+        assert_eq!(
+            classify("xterm", None, Some("Apple_Terminal")),
+            ColorSupportLevel::Colors256
+        );
+        assert_eq!(
+            classify("xterm", None, Some("iTerm.app")),
+            ColorSupportLevel::Colors256
+        );
+        assert_eq!(
+            classify("xterm", None, Some("vscode")),
+            ColorSupportLevel::TrueColor
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_non_windows_ignores_os_release() {
+        let mut environment = environment_with_term("xterm");
+        environment.os_release = String::from("10.0.14931");
         assert_eq!(
             environment.determine_color_level(),
             ColorSupportLevel::Basic
@@ -360,17 +1329,144 @@ mod tests {
     }
 
     #[test]
-    fn test_ci_tf_build() {
-        temp_env::with_vars(
-            [("CI", Some("TF_BUILD")), ("AGENT_NAME", Some("mock_agent"))],
-            || {
-                let environment = Environment::default();
-                assert_eq!(
-                    environment.determine_color_level(),
-                    ColorSupportLevel::Basic
-                );
-            },
+    #[cfg(not(windows))]
+    fn test_non_windows_uses_terminal_heuristics() {
+        assert_eq!(current_os_release(), "");
+        assert_eq!(
+            environment_with_term("xterm-256color").determine_color_level(),
+            ColorSupportLevel::Colors256
+        );
+    }
+
+    #[test]
+    fn test_xterm_version() {
+        let mut environment = environment_with_term("xterm");
+        environment.xterm_version = Some(String::from("XTerm(370)"));
+        assert_eq!(environment.get_xterm_patch(), Some(370));
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::TrueColor
+        );
+
+        environment.xterm_version = Some(String::from("XTerm(300)"));
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+
+        environment.xterm_version = Some(String::from("XTerm"));
+        assert_eq!(environment.get_xterm_patch(), None);
+        assert_eq!(
+            environment.determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+    }
+
+    #[test]
+    fn test_normalize_term() {
+        assert_eq!(normalize_term("xterm-256color\n"), "xterm-256color");
+        assert_eq!(normalize_term(" xterm "), "xterm");
+        assert_eq!(normalize_term("xter\u{7}m\r\n"), "xterm");
+    }
+
+    #[test]
+    fn test_term_with_whitespace_or_control_characters() {
+        with_detection_vars(&[], || {
+            let mut environment = Environment::new(
+                Some(String::from("xterm-256color\n")),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            );
+            assert_eq!(environment.term, "xterm-256color");
+            environment.colorterm = None;
+            environment.ci = None;
+            environment.teamcity_version = None;
+            environment.term_program = None;
+            environment.domterm = None;
+            environment.wt_session = None;
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::Colors256
+            );
+
+            assert_eq!(
+                environment_with_term(" xterm ").determine_terminal_color_level(),
+                ColorSupportLevel::Basic
+            );
+            assert_eq!(classify(" xterm ", None, None), ColorSupportLevel::Basic);
+        });
+    }
+
+    #[test]
+    fn test_color_count_suffixes() {
+        assert_eq!(
+            environment_with_term("screen-16color").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("rxvt-88color").determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+    }
+
+    #[test]
+    fn test_teken() {
+        assert_eq!(
+            environment_with_term("teken").determine_terminal_color_level(),
+            ColorSupportLevel::Basic
+        );
+        assert_eq!(
+            environment_with_term("teken-256color").determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+    }
+
+    #[test]
+    fn test_nsterm() {
+        assert_eq!(
+            environment_with_term("nsterm").determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
         );
+        assert_eq!(
+            environment_with_term("nsterm-build309").determine_terminal_color_level(),
+            ColorSupportLevel::Colors256
+        );
+        for term in ["nsterm-m", "nsterm-m-s", "nsterm-7-m"] {
+            assert_eq!(
+                environment_with_term(term).determine_terminal_color_level(),
+                ColorSupportLevel::NoColor,
+                "TERM={term}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_determine_color_level_basic() {
+        // Clear the host's terminal signals, such as `TERM_PROGRAM`, so only `TERM` is tested.
+        with_detection_vars(&[], || {
+            let mut environment = Environment::default();
+            environment.colorterm = Some(String::from(""));
+            environment.term = String::from("rxvt");
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::Basic
+            );
+        });
+    }
+
+    #[test]
+    fn test_ci_tf_build() {
+        with_detection_vars(&[("CI", "TF_BUILD"), ("AGENT_NAME", "mock_agent")], || {
+            let environment = Environment::default();
+            assert_eq!(
+                environment.determine_terminal_color_level(),
+                ColorSupportLevel::Basic
+            );
+        });
     }
 
     #[test]
@@ -1,8 +1,9 @@
 //! `term_color_support` is a library for detecting and managing color support in terminal environments.
 //!
 //! This crate provides modules for managing color support detection and information (`colors`),
-//! fetching environment details (`environment`), and extracting color support level from
-//! environment variables and command-line flags (`options`).
+//! explaining how a color support level was determined (`detection`), fetching environment details
+//! (`environment`), extracting color support level from environment variables and command-line
+//! flags (`options`), and layering forced levels (`overrides`).
 //!
 //! The `ColorSupport` struct is re-exported for convenient access to color support detection
 //! functionality.
@@ -22,7 +23,10 @@
 //! ```
 
 pub mod colors;
+pub mod detection;
 pub mod environment;
 pub mod options;
+pub mod overrides;
 
-pub use colors::ColorSupport;
+pub use colors::{should_colorize, should_colorize_stderr, ColorSupport};
+pub use environment::classify;
@@ -4,25 +4,43 @@
 //! level information from environment variables and command-line flags.
 //!
 //! The `OutputStreamOptions` struct represents the options for output streams, including whether
-//! the stream is a TTY and whether to sniff flags.
+//! the stream is a TTY and whether to sniff flags, and the `DetectionConfig` struct configures how
+//! detection signals such as `FORCE_COLOR` and flags are combined.
+//!
+//! The `ColorChoice` enum represents a `--color` value such as `auto`, `always`, `never` or a level,
+//! and resolves it to color support information.
 //!
 //! The `has_flag` function checks whether a given command-line flag is present.
 //!
 //! The `extract_force_color_level_from_env` function extracts the color support level from the
-//! `FORCE_COLOR` environment variable.
+//! `FORCE_COLOR` environment variable, and `extract_force_color_level_from_env_named` does the same
+//! for a caller-chosen variable name.
+//!
+//! The `has_no_color_env` function checks whether the `NO_COLOR` environment variable is set.
+//!
+//! The `has_clicolor_force_env` and `extract_clicolor_from_env` functions read the `CLICOLOR_FORCE`
+//! and `CLICOLOR` environment variables.
 //!
 //! The `extract_color_level_from_flags` function extracts the color support level from command-line
 //! flags such as `--color` or `--no-color`.
 
-use crate::colors::ColorSupportLevel;
+use std::io::{stdout, IsTerminal};
+use std::str::FromStr;
 
-/// Struct representing the options for output streams.
+use crate::colors::{
+    determine_stream_color_level_with_args, ColorInfo, ColorSupportLevel,
+    ParseColorSupportLevelError,
+};
+use crate::environment::Environment;
 
+/// Struct representing the options for output streams.
 pub struct OutputStreamOptions {
     /// Specifies whether the output stream is a TTY.
     pub is_tty: bool,
     /// Specifies whether to sniff flags.
     pub sniff_flags: bool,
+    /// Configuration for how detection signals are combined.
+    pub config: DetectionConfig,
 }
 
 impl OutputStreamOptions {
@@ -31,12 +49,111 @@ impl OutputStreamOptions {
         OutputStreamOptions {
             is_tty: is_tty.unwrap_or(false),
             sniff_flags: sniff_flags.unwrap_or(true),
+            config: DetectionConfig::default(),
+        }
+    }
+}
+
+/// Struct representing the configuration for how detection signals are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectionConfig {
+    /// Specifies whether the `FORCE_COLOR` environment variable wins over command-line flags when
+    /// both are present. Defaults to `true`, so e.g. `FORCE_COLOR=0` disables color even if
+    /// `--color=always` is passed; set it to `false` to let the flags win instead.
+    pub force_color_beats_flags: bool,
+    /// Specifies whether to assume an xterm.js-based web terminal, which supports truecolor but
+    /// exposes no single environment signal. When set, a terminal stream is classified as
+    /// truecolor, and so is a stream without an OS TTY if `COLORTERM=truecolor` is set. Defaults
+    /// to `false`.
+    pub assume_web_terminal: bool,
+    /// Specifies whether a `COLORTERM` forwarded over SSH as `LC_COLORTERM` may enable truecolor.
+    /// Defaults to `false`, since any client can set it.
+    pub trust_remote_colorterm: bool,
+    /// Specifies whether PuTTY is assumed to support truecolor, as PuTTY 0.71 and later do.
+    /// Defaults to `false`, classifying PuTTY as 256 colors.
+    pub putty_truecolor: bool,
+    /// Specifies whether the terminfo capability caps the level derived from `COLORTERM`. Only
+    /// has an effect with the `terminfo` feature. Defaults to `false`.
+    pub prefer_terminfo: bool,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        DetectionConfig {
+            force_color_beats_flags: true,
+            assume_web_terminal: false,
+            trust_remote_colorterm: false,
+            putty_truecolor: false,
+            prefer_terminfo: false,
+        }
+    }
+}
+
+/// Enumeration representing a color choice given on the command line, e.g. as a `--color` value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Detect color support automatically.
+    Auto,
+    /// Always emit color.
+    Always,
+    /// Never emit color.
+    Never,
+    /// Emit color at exactly the given level.
+    Level(ColorSupportLevel),
+}
+
+impl ColorChoice {
+    /// Resolves the choice to color support information.
+    ///
+    /// `Auto` runs full detection for stdout, `Never` yields no color, and `Level` yields the given
+    /// level. `Always` ignores whether stdout is a TTY and uses the level the terminal environment
+    /// supports, but at least basic color.
+    pub fn resolve(&self) -> ColorInfo {
+        self.resolve_for(stdout().is_terminal())
+    }
+
+    /// Resolves the choice for a stream with the given TTY status.
+    ///
+    /// The application has already parsed its color flag into this choice, so `Auto` detects
+    /// without looking at the command-line arguments; otherwise the bare `--color` of
+    /// `--color auto` would force color.
+    fn resolve_for(&self, is_tty: bool) -> ColorInfo {
+        match self {
+            ColorChoice::Auto => ColorInfo::new(
+                determine_stream_color_level_with_args(
+                    OutputStreamOptions::new(Some(is_tty), Some(false)),
+                    &[],
+                )
+                .unwrap_or(ColorSupportLevel::NoColor),
+            ),
+            ColorChoice::Always => ColorInfo::new(
+                Environment::default()
+                    .determine_color_level()
+                    .max(ColorSupportLevel::Basic),
+            ),
+            ColorChoice::Never => ColorInfo::new(ColorSupportLevel::NoColor),
+            ColorChoice::Level(level) => ColorInfo::new(*level),
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = ParseColorSupportLevelError;
+
+    /// Parses `auto`, `always` or `never`, or any value accepted by `ColorSupportLevel::from_str`
+    /// such as `none`, `basic`, `256`, `truecolor` or `0` to `3`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => value.parse().map(ColorChoice::Level),
         }
     }
 }
 
 /// Checks whether a given command-line flag is present.
-pub fn has_flag(flag: &str, args: &Vec<String>) -> bool {
+pub fn has_flag(flag: &str, args: &[String]) -> bool {
     let flag_without_dashes = flag.trim_start_matches('-');
 
     args.iter().any(|arg| {
@@ -45,37 +162,78 @@ pub fn has_flag(flag: &str, args: &Vec<String>) -> bool {
     })
 }
 
+/// Reads a variable from the process environment, the default source of detection variables.
+pub(crate) fn process_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
 /// Extracts the color support level from the `FORCE_COLOR` environment variable.
 pub fn extract_force_color_level_from_env() -> Option<ColorSupportLevel> {
-    if let Ok(force_color) = std::env::var("FORCE_COLOR") {
-        if force_color == "true" {
-            return Some(ColorSupportLevel::Basic);
-        }
-        if force_color == "false" {
-            return Some(ColorSupportLevel::NoColor);
-        }
-        if force_color.is_empty() {
-            return Some(ColorSupportLevel::Basic);
-        }
-        if let Ok(level) = force_color.parse::<u32>() {
-            return ColorSupportLevel::from_u32(level);
-        }
-    }
-    None
+    extract_force_color_level_from_env_named("FORCE_COLOR")
+}
+
+/// Extracts the color support level from a `FORCE_COLOR`-like environment variable with the given
+/// name, such as `MYAPP_FORCE_COLOR`.
+pub fn extract_force_color_level_from_env_named(var: &str) -> Option<ColorSupportLevel> {
+    extract_force_color_level_in(&process_var, var)
+}
+
+/// Extracts the color support level from a `FORCE_COLOR`-like variable looked up through `env`.
+pub(crate) fn extract_force_color_level_in(
+    env: &dyn Fn(&str) -> Option<String>,
+    var: &str,
+) -> Option<ColorSupportLevel> {
+    env(var).and_then(|force_color| ColorSupportLevel::parse_force_color(&force_color))
+}
+
+/// Checks whether the `NO_COLOR` environment variable is set to a non-empty value, as described at
+/// <https://no-color.org>.
+pub fn has_no_color_env() -> bool {
+    has_no_color_in(&process_var)
+}
+
+/// Checks whether `NO_COLOR`, looked up through `env`, is set to a non-empty value.
+pub(crate) fn has_no_color_in(env: &dyn Fn(&str) -> Option<String>) -> bool {
+    env("NO_COLOR").is_some_and(|no_color| !no_color.is_empty())
+}
+
+/// Checks whether the `CLICOLOR_FORCE` environment variable is set to a non-empty value other than
+/// `0`, which forces color even when the stream is not a terminal, as described at
+/// <https://bixense.com/clicolors>.
+pub fn has_clicolor_force_env() -> bool {
+    has_clicolor_force_in(&process_var)
+}
+
+/// Checks whether `CLICOLOR_FORCE`, looked up through `env`, is set to a non-empty value other
+/// than `0`.
+pub(crate) fn has_clicolor_force_in(env: &dyn Fn(&str) -> Option<String>) -> bool {
+    env("CLICOLOR_FORCE")
+        .is_some_and(|clicolor_force| !clicolor_force.is_empty() && clicolor_force != "0")
+}
+
+/// Extracts whether the `CLICOLOR` environment variable allows color on terminals. Returns
+/// `Some(false)` for `CLICOLOR=0`, `Some(true)` for any other value, and `None` if it is unset.
+pub fn extract_clicolor_from_env() -> Option<bool> {
+    extract_clicolor_in(&process_var)
+}
+
+/// Extracts whether `CLICOLOR`, looked up through `env`, allows color on terminals.
+pub(crate) fn extract_clicolor_in(env: &dyn Fn(&str) -> Option<String>) -> Option<bool> {
+    env("CLICOLOR").map(|clicolor| clicolor != "0")
 }
 
 /// Extracts the color support level from command-line flags.
-pub fn extract_color_level_from_flags(args: &Vec<String>) -> Option<ColorSupportLevel> {
-    if has_flag("no-color", &args)
-        || has_flag("no-colors", &args)
-        || has_flag("color=false", &args)
-        || has_flag("color=never", &args)
+pub fn extract_color_level_from_flags(args: &[String]) -> Option<ColorSupportLevel> {
+    if has_flag("no-color", args)
+        || has_flag("no-colors", args)
+        || has_flag("color=false", args)
+        || has_flag("color=never", args)
     {
         Some(ColorSupportLevel::NoColor)
-    } else if has_flag("color", &args)
-        || has_flag("colors", &args)
-        || has_flag("color=true", &args)
-        || has_flag("color=always", &args)
+    } else if has_flag("color", args)
+        || has_flag("colors", args)
+        || has_flag("color=true", args)
+        || has_flag("color=always", args)
     {
         Some(ColorSupportLevel::Basic)
     } else {
@@ -86,19 +244,99 @@ pub fn extract_color_level_from_flags(args: &Vec<String>) -> Option<ColorSupport
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::colors::determine_stream_color_level;
+    use crate::detection::with_detection_vars;
 
     #[test]
     fn test_new_output_stream_options_default() {
         let options = OutputStreamOptions::new(None, None);
-        assert_eq!(options.is_tty, false);
-        assert_eq!(options.sniff_flags, true);
+        assert!(!options.is_tty);
+        assert!(options.sniff_flags);
+    }
+
+    #[test]
+    fn test_detection_config_default() {
+        let options = OutputStreamOptions::new(None, None);
+        assert!(options.config.force_color_beats_flags);
+        assert_eq!(options.config, DetectionConfig::default());
     }
 
     #[test]
     fn test_new_output_stream_options_custom() {
         let options = OutputStreamOptions::new(Some(true), Some(false));
-        assert_eq!(options.is_tty, true);
-        assert_eq!(options.sniff_flags, false);
+        assert!(options.is_tty);
+        assert!(!options.sniff_flags);
+    }
+
+    #[test]
+    fn test_color_choice_from_str() {
+        let cases = [
+            ("auto", ColorChoice::Auto),
+            ("always", ColorChoice::Always),
+            ("never", ColorChoice::Never),
+            ("Never", ColorChoice::Never),
+            ("none", ColorChoice::Level(ColorSupportLevel::NoColor)),
+            ("basic", ColorChoice::Level(ColorSupportLevel::Basic)),
+            ("256", ColorChoice::Level(ColorSupportLevel::Colors256)),
+            (
+                "truecolor",
+                ColorChoice::Level(ColorSupportLevel::TrueColor),
+            ),
+            ("0", ColorChoice::Level(ColorSupportLevel::NoColor)),
+            ("1", ColorChoice::Level(ColorSupportLevel::Basic)),
+            ("2", ColorChoice::Level(ColorSupportLevel::Colors256)),
+            ("3", ColorChoice::Level(ColorSupportLevel::TrueColor)),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.parse::<ColorChoice>(), Ok(expected), "{value}");
+        }
+        assert!("sometimes".parse::<ColorChoice>().is_err());
+    }
+
+    #[test]
+    fn test_color_choice_resolve_never_and_level() {
+        assert_eq!(
+            ColorChoice::Never.resolve(),
+            ColorInfo::new(ColorSupportLevel::NoColor)
+        );
+        assert_eq!(
+            ColorChoice::Level(ColorSupportLevel::Colors256).resolve(),
+            ColorInfo::new(ColorSupportLevel::Colors256)
+        );
+    }
+
+    #[test]
+    fn test_color_choice_resolve_always() {
+        with_detection_vars(&[("TERM", "dumb")], || {
+            assert!(ColorChoice::Always.resolve().is_enabled());
+        });
+    }
+
+    #[test]
+    fn test_color_choice_resolve_auto() {
+        with_detection_vars(&[], || {
+            let options = OutputStreamOptions::new(Some(stdout().is_terminal()), Some(false));
+            assert_eq!(
+                ColorChoice::Auto.resolve(),
+                ColorInfo::new(
+                    determine_stream_color_level(options).unwrap_or(ColorSupportLevel::NoColor)
+                )
+            );
+        });
+    }
+
+    #[test]
+    fn test_color_choice_resolve_auto_follows_tty() {
+        with_detection_vars(&[("TERM", "xterm-256color")], || {
+            assert_eq!(
+                ColorChoice::Auto.resolve_for(false),
+                ColorInfo::new(ColorSupportLevel::NoColor)
+            );
+            assert_eq!(
+                ColorChoice::Auto.resolve_for(true),
+                ColorInfo::new(Environment::default().determine_color_level())
+            );
+        });
     }
 
     #[test]
@@ -261,6 +499,112 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_extract_force_color_level_from_env_truecolor_name() {
+        temp_env::with_var("FORCE_COLOR", Some("truecolor"), || {
+            assert_eq!(
+                extract_force_color_level_from_env(),
+                Some(ColorSupportLevel::TrueColor)
+            );
+        });
+    }
+
+    #[test]
+    fn test_extract_force_color_level_from_env_16m_name() {
+        temp_env::with_var("FORCE_COLOR", Some("16m"), || {
+            assert_eq!(
+                extract_force_color_level_from_env(),
+                Some(ColorSupportLevel::TrueColor)
+            );
+        });
+    }
+
+    #[test]
+    fn test_extract_force_color_level_from_env_256_name() {
+        temp_env::with_var("FORCE_COLOR", Some("256"), || {
+            assert_eq!(
+                extract_force_color_level_from_env(),
+                Some(ColorSupportLevel::Colors256)
+            );
+        });
+    }
+
+    #[test]
+    fn test_extract_force_color_level_from_env_named_custom_var() {
+        temp_env::with_vars(
+            [("MYAPP_FORCE_COLOR", Some("3")), ("FORCE_COLOR", None)],
+            || {
+                assert_eq!(
+                    extract_force_color_level_from_env_named("MYAPP_FORCE_COLOR"),
+                    Some(ColorSupportLevel::TrueColor)
+                );
+                assert_eq!(extract_force_color_level_from_env(), None);
+            },
+        );
+    }
+
+    #[test]
+    fn test_extract_force_color_level_from_env_named_custom_var_false() {
+        temp_env::with_var("MYAPP_FORCE_COLOR", Some("false"), || {
+            assert_eq!(
+                extract_force_color_level_from_env_named("MYAPP_FORCE_COLOR"),
+                Some(ColorSupportLevel::NoColor)
+            );
+        });
+    }
+
+    #[test]
+    fn test_extract_force_color_level_from_env_named_unset() {
+        temp_env::with_var_unset("MYAPP_FORCE_COLOR", || {
+            assert_eq!(
+                extract_force_color_level_from_env_named("MYAPP_FORCE_COLOR"),
+                None
+            );
+        });
+    }
+
+    #[test]
+    fn test_has_clicolor_force_env() {
+        temp_env::with_var("CLICOLOR_FORCE", Some("1"), || {
+            assert!(has_clicolor_force_env());
+        });
+        temp_env::with_var("CLICOLOR_FORCE", Some("0"), || {
+            assert!(!has_clicolor_force_env());
+        });
+        temp_env::with_var("CLICOLOR_FORCE", Some(""), || {
+            assert!(!has_clicolor_force_env());
+        });
+        temp_env::with_var_unset("CLICOLOR_FORCE", || {
+            assert!(!has_clicolor_force_env());
+        });
+    }
+
+    #[test]
+    fn test_extract_clicolor_from_env() {
+        temp_env::with_var("CLICOLOR", Some("1"), || {
+            assert_eq!(extract_clicolor_from_env(), Some(true));
+        });
+        temp_env::with_var("CLICOLOR", Some("0"), || {
+            assert_eq!(extract_clicolor_from_env(), Some(false));
+        });
+        temp_env::with_var_unset("CLICOLOR", || {
+            assert_eq!(extract_clicolor_from_env(), None);
+        });
+    }
+
+    #[test]
+    fn test_has_no_color_env() {
+        temp_env::with_var("NO_COLOR", Some("1"), || {
+            assert!(has_no_color_env());
+        });
+        temp_env::with_var("NO_COLOR", Some(""), || {
+            assert!(!has_no_color_env());
+        });
+        temp_env::with_var_unset("NO_COLOR", || {
+            assert!(!has_no_color_env());
+        });
+    }
+
     #[test]
     fn test_extract_color_level_from_flags_no_color_flags() {
         let args = vec![String::from("program_name"), String::from("--no-color")];
@@ -0,0 +1,119 @@
+//! Module for layering forced color support levels.
+//!
+//! This module provides a thread-local stack of forced color support levels, so libraries and
+//! applications can layer overrides on top of each other (e.g. a test harness inside an application
+//! that already forces a level).
+//!
+//! The `push_override` function pushes a level and returns an `OverrideGuard`, which pops it again
+//! when dropped. The top of the stack wins in `determine_stream_color_level`.
+//!
+//! # Example
+//!
+//! ```rust
+//! use term_color_support::colors::ColorSupportLevel;
+//! use term_color_support::overrides::{current_override, push_override};
+//!
+//! let _guard = push_override(ColorSupportLevel::Colors256);
+//! assert_eq!(current_override(), Some(ColorSupportLevel::Colors256));
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+
+use crate::colors::ColorSupportLevel;
+
+thread_local! {
+    /// Pushed levels, each tagged with the id of the guard that owns it.
+    static OVERRIDES: RefCell<Vec<(u64, ColorSupportLevel)>> = const { RefCell::new(Vec::new()) };
+    /// Id handed to the next guard.
+    static NEXT_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Guard returned by `push_override`. Dropping it removes only its own level from the override
+/// stack, so guards may be dropped in any order.
+#[must_use = "the override is removed as soon as the guard is dropped"]
+pub struct OverrideGuard {
+    /// Id of the stack entry owned by this guard.
+    id: u64,
+    /// The stack is thread-local, so the guard must stay on the thread that created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl OverrideGuard {
+    /// Pops the override immediately. Equivalent to dropping the guard.
+    pub fn pop(self) {}
+}
+
+impl Drop for OverrideGuard {
+    fn drop(&mut self) {
+        OVERRIDES.with(|overrides| overrides.borrow_mut().retain(|&(id, _)| id != self.id));
+    }
+}
+
+/// Pushes a forced color support level onto the current thread's override stack.
+pub fn push_override(level: ColorSupportLevel) -> OverrideGuard {
+    let id = NEXT_ID.with(|next_id| next_id.replace(next_id.get() + 1));
+    OVERRIDES.with(|overrides| overrides.borrow_mut().push((id, level)));
+    OverrideGuard {
+        id,
+        _not_send: PhantomData,
+    }
+}
+
+/// Returns the level on top of the current thread's override stack, if any.
+pub fn current_override() -> Option<ColorSupportLevel> {
+    OVERRIDES.with(|overrides| overrides.borrow().last().map(|&(_, level)| level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_override_by_default() {
+        assert_eq!(current_override(), None);
+    }
+
+    #[test]
+    fn test_push_and_pop_override() {
+        let guard = push_override(ColorSupportLevel::Basic);
+        assert_eq!(current_override(), Some(ColorSupportLevel::Basic));
+        guard.pop();
+        assert_eq!(current_override(), None);
+    }
+
+    #[test]
+    fn test_nested_overrides_restore_previous_level() {
+        let _outer = push_override(ColorSupportLevel::TrueColor);
+        {
+            let _inner = push_override(ColorSupportLevel::NoColor);
+            assert_eq!(current_override(), Some(ColorSupportLevel::NoColor));
+        }
+        assert_eq!(current_override(), Some(ColorSupportLevel::TrueColor));
+    }
+
+    #[test]
+    fn test_dropping_outer_guard_keeps_inner_overrides() {
+        let outer = push_override(ColorSupportLevel::Colors256);
+        let inner = push_override(ColorSupportLevel::Basic);
+        drop(outer);
+        assert_eq!(current_override(), Some(ColorSupportLevel::Basic));
+        drop(inner);
+        assert_eq!(current_override(), None);
+    }
+
+    #[test]
+    fn test_out_of_order_drops_remove_only_their_own_level() {
+        let a = push_override(ColorSupportLevel::NoColor);
+        let b = push_override(ColorSupportLevel::Basic);
+        drop(a);
+        let c = push_override(ColorSupportLevel::Colors256);
+        let d = push_override(ColorSupportLevel::TrueColor);
+        drop(b);
+        assert_eq!(current_override(), Some(ColorSupportLevel::TrueColor));
+        drop(d);
+        assert_eq!(current_override(), Some(ColorSupportLevel::Colors256));
+        drop(c);
+        assert_eq!(current_override(), None);
+    }
+}
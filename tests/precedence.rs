@@ -0,0 +1,301 @@
+//! Precedence of the color support signals, from strongest to weakest:
+//!
+//! 1. the override stack (`push_override`),
+//! 2. `FORCE_COLOR` and the last color command-line flag (`FORCE_COLOR` first by default),
+//! 3. `NO_COLOR`,
+//! 4. `CLICOLOR_FORCE`,
+//! 5. `CLICOLOR=0`,
+//! 6. whether the stream is a terminal,
+//! 7. the environment classification (`TERM`, `CI`, ...), which `CLICOLOR=1` leaves unchanged.
+//!
+//! Every test injects its variables through `Detection::detect_with_env`, so none of them depend
+//! on the process environment.
+
+use std::collections::HashMap;
+
+use term_color_support::colors::ColorSupportLevel;
+use term_color_support::detection::{Detection, DetectionReason, Stream};
+use term_color_support::environment::Environment;
+use term_color_support::options::OutputStreamOptions;
+use term_color_support::overrides::push_override;
+
+/// Builds an environment lookup with `TERM=xterm-256color` and then `vars` applied, so the tests
+/// never read the process environment.
+fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let mut env = HashMap::from([(String::from("TERM"), String::from("xterm-256color"))]);
+    env.extend(
+        vars.iter()
+            .map(|&(name, value)| (String::from(name), String::from(value))),
+    );
+    move |name| env.get(name).cloned()
+}
+
+/// Detects stdout with the given variables, flags and terminal status.
+fn detect(vars: &[(&str, &str)], flags: &[&str], is_tty: bool) -> Detection {
+    let args = std::iter::once("program_name")
+        .chain(flags.iter().copied())
+        .map(String::from)
+        .collect::<Vec<String>>();
+    Detection::detect_with_env(
+        Stream::Stdout,
+        OutputStreamOptions::new(Some(is_tty), None),
+        &args,
+        env(vars),
+    )
+}
+
+/// Classifies the environment alone with the given variables.
+fn environment_level(vars: &[(&str, &str)]) -> ColorSupportLevel {
+    Environment::from_env(env(vars)).determine_color_level()
+}
+
+/// Asserts the level and reason of a detection.
+fn assert_detects(
+    vars: &[(&str, &str)],
+    flags: &[&str],
+    is_tty: bool,
+    level: ColorSupportLevel,
+    reason: DetectionReason,
+) {
+    let detection = detect(vars, flags, is_tty);
+    assert_eq!(
+        (detection.level, detection.reason),
+        (level, reason),
+        "vars: {:?}, flags: {:?}, is_tty: {}",
+        vars,
+        flags,
+        is_tty
+    );
+}
+
+#[test]
+fn override_beats_everything() {
+    let _guard = push_override(ColorSupportLevel::Colors256);
+    let vars = [
+        ("FORCE_COLOR", "0"),
+        ("NO_COLOR", "1"),
+        ("CLICOLOR", "0"),
+        ("CLICOLOR_FORCE", "1"),
+        ("CI", "true"),
+    ];
+    for is_tty in [true, false] {
+        assert_detects(
+            &vars,
+            &["--no-color"],
+            is_tty,
+            ColorSupportLevel::Colors256,
+            DetectionReason::Override,
+        );
+    }
+}
+
+#[test]
+fn force_color_beats_flags_and_everything_below() {
+    let vars = [
+        ("FORCE_COLOR", "3"),
+        ("NO_COLOR", "1"),
+        ("CLICOLOR", "0"),
+        ("CI", "true"),
+    ];
+    for is_tty in [true, false] {
+        assert_detects(
+            &vars,
+            &["--no-color"],
+            is_tty,
+            ColorSupportLevel::TrueColor,
+            DetectionReason::ForceColorEnv,
+        );
+    }
+    assert_detects(
+        &[("FORCE_COLOR", "0"), ("CLICOLOR_FORCE", "1")],
+        &["--color=always"],
+        true,
+        ColorSupportLevel::NoColor,
+        DetectionReason::ForceColorEnv,
+    );
+}
+
+#[test]
+fn flags_beat_no_color_and_everything_below() {
+    let vars = [("NO_COLOR", "1"), ("CLICOLOR", "0"), ("CI", "true")];
+    for is_tty in [true, false] {
+        assert_detects(
+            &vars,
+            &["--color=256"],
+            is_tty,
+            ColorSupportLevel::Colors256,
+            DetectionReason::Flag,
+        );
+    }
+    assert_detects(
+        &[("CLICOLOR_FORCE", "1")],
+        &["--no-color"],
+        true,
+        ColorSupportLevel::NoColor,
+        DetectionReason::Flag,
+    );
+}
+
+#[test]
+fn color_auto_flag_cancels_earlier_flags() {
+    assert_detects(
+        &[],
+        &["--color=always", "--color=auto"],
+        false,
+        ColorSupportLevel::NoColor,
+        DetectionReason::NotATerminal,
+    );
+}
+
+#[test]
+fn last_color_flag_wins() {
+    assert_detects(
+        &[],
+        &["--color=auto", "--color=always"],
+        false,
+        ColorSupportLevel::Basic,
+        DetectionReason::Flag,
+    );
+    assert_detects(
+        &[],
+        &["--color=auto", "--no-color"],
+        true,
+        ColorSupportLevel::NoColor,
+        DetectionReason::Flag,
+    );
+}
+
+#[test]
+fn no_color_beats_clicolor_and_terminal() {
+    for is_tty in [true, false] {
+        assert_detects(
+            &[
+                ("NO_COLOR", "1"),
+                ("CLICOLOR_FORCE", "1"),
+                ("CLICOLOR", "1"),
+            ],
+            &[],
+            is_tty,
+            ColorSupportLevel::NoColor,
+            DetectionReason::NoColorEnv,
+        );
+    }
+}
+
+#[test]
+fn empty_no_color_is_ignored() {
+    let detection = detect(&[("NO_COLOR", "")], &[], true);
+    assert_ne!(detection.reason, DetectionReason::NoColorEnv);
+}
+
+#[test]
+fn clicolor_force_beats_clicolor_and_terminal() {
+    let level = environment_level(&[]).max(ColorSupportLevel::Basic);
+    for is_tty in [true, false] {
+        assert_detects(
+            &[("CLICOLOR_FORCE", "1"), ("CLICOLOR", "0")],
+            &[],
+            is_tty,
+            level,
+            DetectionReason::ClicolorForceEnv,
+        );
+    }
+}
+
+#[test]
+fn clicolor_force_enables_color_in_ci() {
+    assert_detects(
+        &[("CLICOLOR_FORCE", "1"), ("CI", "true")],
+        &[],
+        false,
+        environment_level(&[("CI", "true")]).max(ColorSupportLevel::Basic),
+        DetectionReason::ClicolorForceEnv,
+    );
+}
+
+#[test]
+fn clicolor_force_zero_is_ignored() {
+    assert_detects(
+        &[("CLICOLOR_FORCE", "0")],
+        &[],
+        false,
+        ColorSupportLevel::NoColor,
+        DetectionReason::NotATerminal,
+    );
+}
+
+#[test]
+fn clicolor_zero_beats_terminal() {
+    for is_tty in [true, false] {
+        assert_detects(
+            &[("CLICOLOR", "0")],
+            &[],
+            is_tty,
+            ColorSupportLevel::NoColor,
+            DetectionReason::ClicolorEnv,
+        );
+    }
+}
+
+#[test]
+fn not_a_terminal_beats_environment() {
+    for vars in [&[][..], &[("CLICOLOR", "1")], &[("COLORTERM", "truecolor")]] {
+        assert_detects(
+            vars,
+            &[],
+            false,
+            ColorSupportLevel::NoColor,
+            DetectionReason::NotATerminal,
+        );
+    }
+}
+
+#[test]
+fn terminal_uses_environment_classification() {
+    for vars in [&[][..], &[("CI", "true")], &[("COLORTERM", "truecolor")]] {
+        assert_detects(
+            vars,
+            &[],
+            true,
+            environment_level(vars),
+            DetectionReason::Environment,
+        );
+    }
+}
+
+#[test]
+#[cfg(not(windows))]
+fn ci_disables_color_on_terminal() {
+    assert_detects(
+        &[("CI", "true")],
+        &[],
+        true,
+        ColorSupportLevel::NoColor,
+        DetectionReason::Environment,
+    );
+}
+
+#[test]
+#[cfg(not(windows))]
+fn clicolor_one_does_not_enable_color() {
+    for vars in [
+        &[("TERM", "dumb"), ("CLICOLOR", "1")][..],
+        &[("TERM", "unknown"), ("CLICOLOR", "1")],
+        &[("CI", "true"), ("CLICOLOR", "1")],
+    ] {
+        assert_detects(
+            vars,
+            &[],
+            true,
+            ColorSupportLevel::NoColor,
+            DetectionReason::Environment,
+        );
+    }
+    assert_detects(
+        &[("CLICOLOR", "1")],
+        &[],
+        true,
+        ColorSupportLevel::Colors256,
+        DetectionReason::Environment,
+    );
+}